@@ -1,12 +1,17 @@
 use axum::{
     Json, Router,
+    body::Body,
     extract::{Path, Query, State},
-    routing::get,
+    http::{Request, StatusCode, header},
+    middleware::{self, Next},
+    response::Response,
+    routing::{get, post},
 };
 use clap::Parser;
-use lametrobusping::{ChunkFile, Record, SystemStats, ensure_data_dir};
+use lametrobusping::{Percentiles, Record, SystemStats, ensure_data_dir};
 use std::{
     collections::{HashMap, VecDeque},
+    path::PathBuf,
     sync::{Arc, RwLock},
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
@@ -14,6 +19,14 @@ use tokio::time;
 
 const RETENTION_SECONDS: u64 = 48 * 3600;
 
+// Trend detection: a bus is "emerging" when its recent rate of high-rank records
+// outpaces its own longer-run baseline rate, rather than just being chronically bad.
+const TREND_THRESHOLD_RANK: u8 = 90;
+const TREND_RECENT_WINDOW_SECONDS: u64 = 10 * 60;
+const TREND_BASELINE_WINDOW_SECONDS: u64 = 60 * 60;
+const TREND_WINDOW_SECONDS: u64 = TREND_RECENT_WINDOW_SECONDS + TREND_BASELINE_WINDOW_SECONDS;
+const DEFAULT_TRENDING_TOP_N: usize = 20;
+
 #[derive(Clone)]
 struct AppState {
     // BusID -> History (Oldest first)
@@ -24,6 +37,20 @@ struct AppState {
     last_loaded_ts: Arc<RwLock<u64>>,
     // Rank (0-100) -> List of (Timestamp, BusId)
     anomalies: Arc<RwLock<HashMap<u8, VecDeque<(u64, String)>>>>,
+    // Count of chunk files merged into state since startup
+    chunks_loaded: Arc<RwLock<u64>>,
+    // BusID -> rolling accumulator of recent high-rank events, for trend detection
+    trends: Arc<RwLock<HashMap<String, TrendAccumulator>>>,
+    // Shared secret required on data routes via `Authorization: Bearer <secret>`, if configured
+    auth_secret: Arc<Option<String>>,
+}
+
+/// Rolling window of high-rank ("anomalous") event timestamps for one bus, oldest first.
+/// Capped to `TREND_WINDOW_SECONDS` so trend scoring never has to rescan `history`.
+struct TrendAccumulator {
+    events: VecDeque<u64>,
+    // Newest end_of_interval of this bus already merged, so each tick only scans its new records
+    last_merged_ts: u64,
 }
 
 #[derive(Parser, Debug)]
@@ -32,6 +59,61 @@ struct Args {
     /// Port to listen on
     #[arg(short, long, default_value_t = 3000)]
     port: u16,
+
+    /// Shared secret required on data routes, passed inline
+    #[arg(long)]
+    auth_secret: Option<String>,
+
+    /// Path to a file holding the shared secret required on data routes
+    #[arg(long)]
+    auth_secret_file: Option<PathBuf>,
+}
+
+/// Resolves the configured auth secret, if any. Reading it from a file (rather than
+/// accepting it only inline) keeps it out of process args and env dumps, e.g. when
+/// mounted from a Kubernetes/Docker secret.
+fn load_auth_secret(args: &Args) -> Result<Option<String>, Box<dyn std::error::Error>> {
+    match (&args.auth_secret, &args.auth_secret_file) {
+        (Some(_), Some(_)) => {
+            Err("only one of --auth-secret or --auth-secret-file may be set".into())
+        }
+        (Some(secret), None) => Ok(Some(secret.clone())),
+        (None, Some(path)) => Ok(Some(std::fs::read_to_string(path)?.trim().to_string())),
+        (None, None) => Ok(None),
+    }
+}
+
+/// Rejects requests missing a matching `Authorization: Bearer <secret>` header.
+/// A no-op when no secret is configured, preserving today's open-by-default behavior.
+async fn require_auth(
+    State(state): State<AppState>,
+    req: Request<Body>,
+    next: Next,
+) -> Result<Response, StatusCode> {
+    let Some(expected) = state.auth_secret.as_ref() else {
+        return Ok(next.run(req).await);
+    };
+
+    let provided = req
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+
+    match provided {
+        Some(token) if constant_time_eq(token, expected) => Ok(next.run(req).await),
+        _ => Err(StatusCode::UNAUTHORIZED),
+    }
+}
+
+/// Compares two strings in constant time, so a mismatching bearer token doesn't leak how
+/// many leading bytes of the secret the caller guessed correctly.
+fn constant_time_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b.iter()).fold(0u8, |diff, (x, y)| diff | (x ^ y)) == 0
 }
 
 #[derive(serde::Deserialize)]
@@ -47,17 +129,59 @@ struct ScoredBus {
     history: Vec<Record>,
 }
 
+#[derive(serde::Deserialize)]
+struct TrendingQuery {
+    top: Option<usize>,
+}
+
+#[derive(serde::Serialize)]
+struct TrendingBus {
+    bus_id: String,
+    recent_rate: f64,   // high-rank events/sec over the last TREND_RECENT_WINDOW_SECONDS
+    baseline_rate: f64, // high-rank events/sec over the preceding TREND_BASELINE_WINDOW_SECONDS
+    score: f64,         // recent_rate / baseline_rate, baseline floored at 1 event per baseline window
+}
+
+const DEFAULT_BATCH_LIMIT: usize = 500;
+
+#[derive(serde::Deserialize)]
+struct BatchRequest {
+    bus_ids: Vec<String>,
+    since: Option<u64>,
+    until: Option<u64>,
+    limit: Option<usize>,
+    cursor: Option<String>,
+}
+
+#[derive(serde::Serialize)]
+struct BatchResponse {
+    // BusID -> Records in range, newest-first, truncated to `limit`
+    buses: HashMap<String, Vec<Record>>,
+    // Opaque cursor to pass back as `cursor` to fetch the next page. Present whenever this
+    // page reached `limit` (even if no records follow, in which case the next page is empty);
+    // absent when the page ended before reaching `limit`.
+    cursor: Option<String>,
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let args = Args::parse();
     ensure_data_dir()?;
     println!("Starting API...");
 
+    let auth_secret = load_auth_secret(&args)?;
+    if auth_secret.is_some() {
+        println!("Auth enabled: data routes require a matching Authorization header");
+    }
+
     let state = AppState {
         history: Arc::new(RwLock::new(HashMap::new())),
         stats: Arc::new(RwLock::new(VecDeque::new())),
         last_loaded_ts: Arc::new(RwLock::new(0)),
         anomalies: Arc::new(RwLock::new(HashMap::new())),
+        chunks_loaded: Arc::new(RwLock::new(0)),
+        trends: Arc::new(RwLock::new(HashMap::new())),
+        auth_secret: Arc::new(auth_secret),
     };
 
     // Initial load
@@ -78,10 +202,30 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
-    let app = Router::new()
+    // Background trend detection
+    let trend_state = state.clone();
+    tokio::spawn(async move {
+        let mut interval = time::interval(Duration::from_secs(30));
+        loop {
+            interval.tick().await;
+            if let Err(e) = update_trends(trend_state.clone()) {
+                eprintln!("Error updating trends: {}", e);
+            }
+        }
+    });
+
+    // Auth only gates the data routes; /metrics stays reachable for scraping.
+    let data_routes = Router::new()
         .route("/history/:bus_id", get(get_history))
         .route("/stats", get(get_stats))
         .route("/anomalies", get(get_anomalies))
+        .route("/trending", get(get_trending))
+        .route("/batch", post(post_batch))
+        .route_layer(middleware::from_fn_with_state(state.clone(), require_auth));
+
+    let app = Router::new()
+        .merge(data_routes)
+        .route("/metrics", get(get_metrics))
         .layer(tower_http::cors::CorsLayer::permissive())
         .with_state(state);
 
@@ -110,14 +254,14 @@ fn load_recent_chunks(state: AppState) -> Result<(), Box<dyn std::error::Error>>
                     if ts > last_loaded {
                         // Load this file
                         let data = std::fs::read(&path)?;
-                        let chunk_res: Result<ChunkFile, _> = bincode::deserialize(&data);
+                        let chunk_res = lametrobusping::load_chunk(&data);
 
-                        if let Ok(chunk) = chunk_res {
+                        if let Some((chunk_stats, records)) = chunk_res {
                             // Merge into state
                             {
                                 let mut history = state.history.write().unwrap();
                                 let mut anomalies = state.anomalies.write().unwrap();
-                                for (bus_id, records) in chunk.records {
+                                for (bus_id, records) in records {
                                     let entry = history.entry(bus_id.clone()).or_default();
                                     for record in records {
                                         // Indexing
@@ -132,10 +276,11 @@ fn load_recent_chunks(state: AppState) -> Result<(), Box<dyn std::error::Error>>
 
                             {
                                 let mut stats = state.stats.write().unwrap();
-                                stats.push_back(chunk.stats);
+                                stats.push_back(chunk_stats);
                             }
 
                             *state.last_loaded_ts.write().unwrap() = ts;
+                            *state.chunks_loaded.write().unwrap() += 1;
                             println!("Loaded chunk: {}", ts);
                         } else {
                             eprintln!(
@@ -210,6 +355,66 @@ fn prune_memory(state: AppState) -> Result<(), Box<dyn std::error::Error>> {
     Ok(())
 }
 
+/// Merges records that arrived since the last tick into the per-bus trend accumulators,
+/// then prunes anything outside `TREND_WINDOW_SECONDS`. Scans each bus's history back-to-front
+/// and stops at the first record already seen *for that bus*, so this never rescans the full
+/// history — the watermark is tracked per bus, since fast- and slow-reporting buses otherwise
+/// have no relation to each other's timestamps.
+fn update_trends(state: AppState) -> Result<(), Box<dyn std::error::Error>> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+
+    {
+        let history = state.history.read().unwrap();
+        let mut trends = state.trends.write().unwrap();
+        for (bus_id, records) in history.iter() {
+            let last_ts = trends.get(bus_id).map(|a| a.last_merged_ts).unwrap_or(0);
+            let mut newest_ts = last_ts;
+
+            // Walk newest-first, stopping as soon as we reach records this bus already merged.
+            let mut new_events = Vec::new();
+            for record in records.iter().rev() {
+                if record.end_of_interval <= last_ts {
+                    break;
+                }
+                newest_ts = newest_ts.max(record.end_of_interval);
+                if record.rank >= TREND_THRESHOLD_RANK {
+                    new_events.push(record.end_of_interval);
+                }
+            }
+
+            if newest_ts > last_ts {
+                let accumulator = trends.entry(bus_id.clone()).or_insert_with(|| TrendAccumulator {
+                    events: VecDeque::new(),
+                    last_merged_ts: 0,
+                });
+                // `new_events` was collected newest-first; append oldest-first to keep the
+                // accumulator ordered, matching the rest of `records`.
+                accumulator.events.extend(new_events.into_iter().rev());
+                accumulator.last_merged_ts = newest_ts;
+            }
+        }
+    }
+
+    {
+        let cutoff = now.saturating_sub(TREND_WINDOW_SECONDS);
+        let mut trends = state.trends.write().unwrap();
+
+        // Only prune stale events, not the accumulator itself: dropping a bus entirely would
+        // lose its `last_merged_ts` watermark and force a full history rescan for it later.
+        for accumulator in trends.values_mut() {
+            while let Some(&front) = accumulator.events.front() {
+                if front < cutoff {
+                    accumulator.events.pop_front();
+                } else {
+                    break;
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
 async fn get_history(
     State(state): State<AppState>,
     Path(bus_id): Path<String>,
@@ -274,3 +479,224 @@ async fn get_anomalies(
 
     Json(scored_buses)
 }
+
+/// Ranks buses by how much their recent rate of high-rank records exceeds their own
+/// baseline rate, so buses that just started degrading surface even if chronically
+/// bad buses already dominate `/anomalies`.
+async fn get_trending(
+    State(state): State<AppState>,
+    Query(query): Query<TrendingQuery>,
+) -> Json<Vec<TrendingBus>> {
+    let top = query.top.unwrap_or(DEFAULT_TRENDING_TOP_N);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let recent_cutoff = now.saturating_sub(TREND_RECENT_WINDOW_SECONDS);
+    let baseline_cutoff = now.saturating_sub(TREND_WINDOW_SECONDS);
+
+    let trends = state.trends.read().unwrap();
+    let mut scored = Vec::new();
+
+    for (bus_id, accumulator) in trends.iter() {
+        let mut recent_count = 0u32;
+        let mut baseline_count = 0u32;
+        for &ts in &accumulator.events {
+            if ts >= recent_cutoff {
+                recent_count += 1;
+            } else if ts >= baseline_cutoff {
+                baseline_count += 1;
+            }
+        }
+
+        if recent_count == 0 {
+            continue;
+        }
+
+        let recent_rate = recent_count as f64 / TREND_RECENT_WINDOW_SECONDS as f64;
+        let baseline_rate = baseline_count as f64 / TREND_BASELINE_WINDOW_SECONDS as f64;
+        // Floor the denominator so a bus with zero baseline history doesn't score as infinite.
+        let score = recent_rate / baseline_rate.max(1.0 / TREND_BASELINE_WINDOW_SECONDS as f64);
+
+        scored.push(TrendingBus {
+            bus_id: bus_id.clone(),
+            recent_rate,
+            baseline_rate,
+            score,
+        });
+    }
+
+    scored.sort_by(|a, b| b.score.partial_cmp(&a.score).unwrap_or(std::cmp::Ordering::Equal));
+    scored.truncate(top);
+
+    Json(scored)
+}
+
+/// Reads several buses' histories in one request, filtered to a time range and paginated.
+///
+/// `cursor`, if present, resumes a previous call: it encodes the `(bus_id, end_of_interval)`
+/// of the last record that call returned, so the scan picks up right after it.
+async fn post_batch(
+    State(state): State<AppState>,
+    Json(req): Json<BatchRequest>,
+) -> Json<BatchResponse> {
+    let limit = req.limit.unwrap_or(DEFAULT_BATCH_LIMIT).max(1);
+    let since = req.since.unwrap_or(0);
+    let until = req.until.unwrap_or(u64::MAX);
+    let resume = req.cursor.as_deref().and_then(decode_cursor);
+
+    let history = state.history.read().unwrap();
+    let mut buses: HashMap<String, Vec<Record>> = HashMap::new();
+    let mut remaining = limit;
+    let mut next_cursor = None;
+    // Skip bus_ids preceding the one the cursor left off on
+    let mut skipping_to = resume.as_ref().map(|(bus_id, _)| bus_id.clone());
+
+    'outer: for bus_id in &req.bus_ids {
+        if let Some(target) = &skipping_to {
+            if bus_id != target {
+                continue;
+            }
+        }
+
+        let Some(records) = history.get(bus_id) else {
+            skipping_to = None;
+            continue;
+        };
+
+        let mut matches: Vec<&Record> = records
+            .iter()
+            .filter(|r| r.end_of_interval >= since && r.end_of_interval <= until)
+            .collect();
+        matches.sort_by(|a, b| b.end_of_interval.cmp(&a.end_of_interval));
+
+        if let Some((target, resume_ts)) = &resume {
+            if bus_id == target {
+                matches.retain(|r| r.end_of_interval < *resume_ts);
+            }
+        }
+        skipping_to = None;
+
+        let mut page = Vec::new();
+        for record in matches {
+            page.push(record.clone());
+            remaining -= 1;
+            if remaining == 0 {
+                next_cursor = Some(encode_cursor(bus_id, record.end_of_interval));
+                break;
+            }
+        }
+
+        if !page.is_empty() {
+            buses.insert(bus_id.clone(), page);
+        }
+
+        if next_cursor.is_some() {
+            break 'outer;
+        }
+    }
+
+    Json(BatchResponse {
+        buses,
+        cursor: next_cursor,
+    })
+}
+
+/// Encodes a continuation cursor as an opaque hex string.
+fn encode_cursor(bus_id: &str, end_of_interval: u64) -> String {
+    let raw = format!("{}\u{1}{}", bus_id, end_of_interval);
+    raw.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a cursor produced by `encode_cursor`, ignoring anything malformed.
+fn decode_cursor(cursor: &str) -> Option<(String, u64)> {
+    if cursor.len() % 2 != 0 {
+        return None;
+    }
+    let bytes: Option<Vec<u8>> = (0..cursor.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+        .collect();
+    let raw = String::from_utf8(bytes?).ok()?;
+    let (bus_id, ts) = raw.split_once('\u{1}')?;
+    Some((bus_id.to_string(), ts.parse().ok()?))
+}
+
+/// Renders the current in-memory state as Prometheus text exposition format.
+async fn get_metrics(State(state): State<AppState>) -> String {
+    let mut out = String::new();
+
+    {
+        let stats = state.stats.read().unwrap();
+        if let Some(latest) = stats.back() {
+            push_quantiles(&mut out, "lametrobusping_interval_seconds", "Latest interval stats", &latest.interval_stats);
+            push_quantiles(&mut out, "lametrobusping_latency_seconds", "Latest latency stats", &latest.latency_stats);
+
+            out.push_str("# HELP lametrobusping_sample_count Number of samples in the latest chunk.\n");
+            out.push_str("# TYPE lametrobusping_sample_count gauge\n");
+            out.push_str(&format!("lametrobusping_sample_count {}\n", latest.sample_count));
+        }
+    }
+
+    {
+        let history = state.history.read().unwrap();
+        let bus_count = history.len();
+        let record_count: usize = history.values().map(|records| records.len()).sum();
+
+        out.push_str("# HELP lametrobusping_tracked_buses Number of buses currently held in memory.\n");
+        out.push_str("# TYPE lametrobusping_tracked_buses gauge\n");
+        out.push_str(&format!("lametrobusping_tracked_buses {}\n", bus_count));
+
+        out.push_str("# HELP lametrobusping_buffered_records Total number of buffered records across all tracked buses.\n");
+        out.push_str("# TYPE lametrobusping_buffered_records gauge\n");
+        out.push_str(&format!("lametrobusping_buffered_records {}\n", record_count));
+    }
+
+    {
+        let anomalies = state.anomalies.read().unwrap();
+        out.push_str("# HELP lametrobusping_anomaly_count Number of buffered anomaly records, labeled by rank bucket.\n");
+        out.push_str("# TYPE lametrobusping_anomaly_count gauge\n");
+        let mut ranks: Vec<&u8> = anomalies.keys().collect();
+        ranks.sort();
+        for rank in ranks {
+            let count = anomalies[rank].len();
+            out.push_str(&format!(
+                "lametrobusping_anomaly_count{{rank=\"{}\"}} {}\n",
+                rank, count
+            ));
+        }
+    }
+
+    out.push_str("# HELP lametrobusping_last_loaded_timestamp_seconds Unix timestamp of the most recently loaded chunk.\n");
+    out.push_str("# TYPE lametrobusping_last_loaded_timestamp_seconds gauge\n");
+    out.push_str(&format!(
+        "lametrobusping_last_loaded_timestamp_seconds {}\n",
+        *state.last_loaded_ts.read().unwrap()
+    ));
+
+    out.push_str("# HELP lametrobusping_chunks_loaded_total Number of chunk files merged into state since startup.\n");
+    out.push_str("# TYPE lametrobusping_chunks_loaded_total counter\n");
+    out.push_str(&format!(
+        "lametrobusping_chunks_loaded_total {}\n",
+        *state.chunks_loaded.read().unwrap()
+    ));
+
+    out
+}
+
+/// Appends p50/p90/p95/p99 gauges for a `Percentiles` distribution under `metric_name`, labeled by quantile.
+fn push_quantiles(out: &mut String, metric_name: &str, help: &str, percentiles: &Percentiles) {
+    out.push_str(&format!("# HELP {} {}.\n", metric_name, help));
+    out.push_str(&format!("# TYPE {} gauge\n", metric_name));
+    for (quantile, value) in [
+        ("0.5", percentiles.p50),
+        ("0.9", percentiles.p90),
+        ("0.95", percentiles.p95),
+        ("0.99", percentiles.p99),
+    ] {
+        out.push_str(&format!(
+            "{}{{quantile=\"{}\"}} {}\n",
+            metric_name, quantile, value
+        ));
+    }
+}
@@ -124,13 +124,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 }
             }
 
-            let chunk_file = ChunkFile {
-                stats,
-                records: current_chunk.clone(),
-            };
+            let chunk_file = ChunkFile::from_records(stats, &current_chunk);
 
             // Write file
-            let params = bincode::serialize(&chunk_file)?;
+            let params = lametrobusping::serialize_chunk(&chunk_file)?;
             let filename = format!("./data/chunk_{}.bin", chunk_ts);
             std::fs::write(&filename, params)?;
             println!(
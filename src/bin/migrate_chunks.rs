@@ -0,0 +1,61 @@
+// One-shot migration: rewrites existing `chunk_*.bin` files from the legacy
+// flat-blob format into the current columnar, delta-encoded `ChunkFile` layout.
+use lametrobusping::{CHUNK_FORMAT_VERSION, ChunkFile, LegacyChunkFile, serialize_chunk};
+use std::path::Path;
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let mut migrated = 0u32;
+    let mut already_current = 0u32;
+    let mut skipped = 0u32;
+
+    for entry in glob::glob("./data/chunk_*.bin")? {
+        let path = match entry {
+            Ok(path) => path,
+            Err(e) => {
+                eprintln!("Error reading glob entry: {}", e);
+                skipped += 1;
+                continue;
+            }
+        };
+
+        match migrate_one(&path) {
+            Ok(true) => migrated += 1,
+            Ok(false) => already_current += 1,
+            Err(e) => {
+                eprintln!("Skipping {}: {}", path.display(), e);
+                skipped += 1;
+            }
+        }
+    }
+
+    println!(
+        "Migration complete: {} migrated, {} already current, {} skipped",
+        migrated, already_current, skipped
+    );
+    Ok(())
+}
+
+/// Rewrites one chunk file into the current layout. Returns `Ok(true)` if it was migrated,
+/// `Ok(false)` if it was already current. Leaves the original file untouched on any error.
+fn migrate_one(path: &Path) -> Result<bool, Box<dyn std::error::Error>> {
+    let data = std::fs::read(path)?;
+
+    if let Some((&version, rest)) = data.split_first() {
+        if version == CHUNK_FORMAT_VERSION && bincode::deserialize::<ChunkFile>(rest).is_ok() {
+            return Ok(false);
+        }
+    }
+
+    let legacy: LegacyChunkFile = bincode::deserialize(&data)?;
+    let chunk = ChunkFile::from_records(legacy.stats, &legacy.records);
+    let bytes = serialize_chunk(&chunk)?;
+
+    // Write to a temp file and rename over the original so a crash or full disk
+    // mid-write can't leave the chunk truncated or corrupted.
+    let tmp_path = path.with_extension("bin.tmp");
+    std::fs::write(&tmp_path, bytes)?;
+    std::fs::rename(&tmp_path, path)?;
+
+    println!("Migrated {}", path.display());
+    Ok(true)
+}
@@ -79,13 +79,185 @@ impl Percentiles {
     }
 }
 
-/// The file format for a 1-minute data chunk.
+/// On-disk format written by the current code. Bumped whenever `ChunkFile`'s layout changes.
+pub const CHUNK_FORMAT_VERSION: u8 = 2;
+
+/// One bus's records, stored as parallel columns (varint-encoded numbers, one byte per rank)
+/// instead of `Vec<Record>` so
+/// that the repeated/adjacent values within a column compress and dedup far better on disk.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BusColumns {
+    pub bus_id: String,
+    pub count: u32,
+    /// `end_of_interval - ChunkFile::base_ts`, varint-encoded per record
+    pub end_of_interval_deltas: Vec<u8>,
+    /// `interval`, varint-encoded per record
+    pub intervals: Vec<u8>,
+    /// `latency`, varint-encoded per record
+    pub latencies: Vec<u8>,
+    /// `rank`, one byte per record
+    pub ranks: Vec<u8>,
+}
+
+impl BusColumns {
+    fn encode(bus_id: String, base_ts: u64, records: &[Record]) -> BusColumns {
+        let mut end_of_interval_deltas = Vec::new();
+        let mut intervals = Vec::new();
+        let mut latencies = Vec::new();
+        let mut ranks = Vec::with_capacity(records.len());
+
+        for record in records {
+            varint::encode(record.end_of_interval.saturating_sub(base_ts), &mut end_of_interval_deltas);
+            varint::encode(record.interval as u64, &mut intervals);
+            varint::encode(record.latency as u64, &mut latencies);
+            ranks.push(record.rank);
+        }
+
+        BusColumns {
+            bus_id,
+            count: records.len() as u32,
+            end_of_interval_deltas,
+            intervals,
+            latencies,
+            ranks,
+        }
+    }
+
+    fn decode(&self, base_ts: u64) -> Vec<Record> {
+        let mut deltas_pos = 0;
+        let mut intervals_pos = 0;
+        let mut latencies_pos = 0;
+        let mut records = Vec::with_capacity(self.count as usize);
+
+        for i in 0..self.count as usize {
+            let delta = varint::decode(&self.end_of_interval_deltas, &mut deltas_pos).unwrap_or(0);
+            let interval = varint::decode(&self.intervals, &mut intervals_pos).unwrap_or(0) as u16;
+            let latency = varint::decode(&self.latencies, &mut latencies_pos).unwrap_or(0) as u16;
+            let rank = self.ranks.get(i).copied().unwrap_or(0);
+
+            records.push(Record {
+                interval,
+                end_of_interval: base_ts + delta,
+                latency,
+                rank,
+            });
+        }
+
+        records
+    }
+}
+
+/// The file format for a 1-minute data chunk: columns per bus, delta-encoded against
+/// `base_ts`. Written to disk behind a leading [`CHUNK_FORMAT_VERSION`] byte (see
+/// [`serialize_chunk`] / [`load_chunk`]).
 #[derive(Serialize, Deserialize, Clone, Debug)]
 pub struct ChunkFile {
+    pub stats: SystemStats,
+    pub base_ts: u64,
+    pub buses: Vec<BusColumns>,
+}
+
+impl ChunkFile {
+    /// Builds a `ChunkFile` from the in-memory per-bus record lists the ingester buffers
+    /// through a flush interval.
+    pub fn from_records(stats: SystemStats, records: &HashMap<String, Vec<Record>>) -> ChunkFile {
+        let base_ts = records
+            .values()
+            .flatten()
+            .map(|r| r.end_of_interval)
+            .min()
+            .unwrap_or(0);
+
+        let buses = records
+            .iter()
+            .map(|(bus_id, recs)| BusColumns::encode(bus_id.clone(), base_ts, recs))
+            .collect();
+
+        ChunkFile {
+            stats,
+            base_ts,
+            buses,
+        }
+    }
+
+    /// Expands the columnar layout back into the `BusID -> Vec<Record>` shape callers want.
+    pub fn into_records(self) -> HashMap<String, Vec<Record>> {
+        let base_ts = self.base_ts;
+        self.buses
+            .into_iter()
+            .map(|columns| {
+                let records = columns.decode(base_ts);
+                (columns.bus_id, records)
+            })
+            .collect()
+    }
+}
+
+/// The original (pre-v2) on-disk format: a flat bincode blob of a full record list per bus.
+/// Kept only so [`load_chunk`] can still read chunks written before the columnar migration;
+/// see the `migrate_chunks` binary for rewriting old files into the current layout.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct LegacyChunkFile {
     pub stats: SystemStats,
     pub records: HashMap<String, Vec<Record>>,
 }
 
+/// Serializes a chunk with its leading format-version byte, ready to write to disk.
+pub fn serialize_chunk(chunk: &ChunkFile) -> Result<Vec<u8>, Box<bincode::ErrorKind>> {
+    let mut out = Vec::with_capacity(1);
+    out.push(CHUNK_FORMAT_VERSION);
+    out.extend(bincode::serialize(chunk)?);
+    Ok(out)
+}
+
+/// Reads a chunk file's bytes, trying the current columnar format first and falling back to
+/// the legacy full-blob format for chunks written before the migration. Returns `None` if
+/// neither parses (the existing "schema mismatch" case).
+pub fn load_chunk(data: &[u8]) -> Option<(SystemStats, HashMap<String, Vec<Record>>)> {
+    if let Some((&version, rest)) = data.split_first() {
+        if version == CHUNK_FORMAT_VERSION {
+            if let Ok(chunk) = bincode::deserialize::<ChunkFile>(rest) {
+                let stats = chunk.stats.clone();
+                return Some((stats, chunk.into_records()));
+            }
+        }
+    }
+
+    bincode::deserialize::<LegacyChunkFile>(data)
+        .ok()
+        .map(|legacy| (legacy.stats, legacy.records))
+}
+
+/// Minimal LEB128 varint encoding used to pack [`BusColumns`]' numeric columns.
+mod varint {
+    pub fn encode(mut value: u64, out: &mut Vec<u8>) {
+        loop {
+            let byte = (value & 0x7f) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(byte);
+                break;
+            } else {
+                out.push(byte | 0x80);
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8], pos: &mut usize) -> Option<u64> {
+        let mut value: u64 = 0;
+        let mut shift = 0;
+        loop {
+            let byte = *bytes.get(*pos)?;
+            *pos += 1;
+            value |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Some(value);
+            }
+            shift += 7;
+        }
+    }
+}
+
 // Ensure the data directory exists
 pub fn ensure_data_dir() -> std::io::Result<()> {
     use std::fs;